@@ -1,13 +1,143 @@
 use rpassword;
 use pam_client2::{Context, Flag};
 use pam_client2::conv_cli::Conversation;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{rand_core::OsRng, SaltString};
 use std::io::{self, Write};
 use std::fs::{read_to_string, write, create_dir_all};
 use std::path::PathBuf;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::logs::{log_debug, log_error, log_info};
+use crate::config::AuthBackendKind;
 use crate::Config;
 
+/// A pluggable source of truth for "does this password belong to this user".
+pub trait AuthBackend {
+    fn authenticate(&self, user: &str) -> Result<bool, String>;
+}
+
+/// Authenticates against the system's PAM stack (`/etc/pam.d/elev`).
+pub struct PamBackend;
+
+impl AuthBackend for PamBackend {
+    fn authenticate(&self, user: &str) -> Result<bool, String> {
+        let mut ctx = Context::new("elev", Some(user), CustomConversation {
+            prompt: format!("[ elev ] Please enter password for {}: ", user),
+        }).map_err(|e| format!("PAM init failed: {}", e))?;
+
+        if let Err(e) = ctx.authenticate(Flag::NONE) {
+            return Err(format!("PAM authentication failed: {}", e));
+        }
+
+        if let Err(e) = ctx.acct_mgmt(Flag::NONE) {
+            return Err(format!("Account validation failed: {}", e));
+        }
+
+        let _ = ctx.open_session(Flag::NONE);
+        Ok(true)
+    }
+}
+
+/// Authenticates against a local `username:argon2_phc_hash` credential file.
+pub struct FileBackend {
+    pub path: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileBackend { path: path.into() }
+    }
+
+    /// Rejects the credential file unless it's root-owned with no group/other
+    /// permission bits set (e.g. mode 0600).
+    fn check_permissions(&self) -> Result<(), String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = std::fs::metadata(&self.path)
+            .map_err(|e| format!("Failed to stat credential file '{}': {}", self.path.display(), e))?;
+
+        if meta.uid() != 0 {
+            return Err(format!("Credential file '{}' must be owned by root", self.path.display()));
+        }
+        if meta.mode() & 0o077 != 0 {
+            return Err(format!(
+                "Credential file '{}' must not be readable or writable by group/other (expected mode 0600)",
+                self.path.display()
+            ));
+        }
+        Ok(())
+    }
+
+    fn find_hash(&self, user: &str) -> Result<String, String> {
+        let content = read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read credential file '{}': {}", self.path.display(), e))?;
+        for line in content.lines() {
+            if let Some((name, hash)) = line.split_once(':') {
+                if name == user {
+                    return Ok(hash.trim().to_string());
+                }
+            }
+        }
+        Err(format!("No credential entry for user '{}'", user))
+    }
+}
+
+impl AuthBackend for FileBackend {
+    fn authenticate(&self, user: &str) -> Result<bool, String> {
+        self.check_permissions()?;
+        let stored_hash = self.find_hash(user)?;
+        let parsed_hash = PasswordHash::new(&stored_hash)
+            .map_err(|e| format!("Malformed credential entry for '{}': {}", user, e))?;
+
+        print!("[ elev ] Please enter password for {}: ", user);
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let password = rpassword::read_password().map_err(|e| e.to_string())?;
+
+        match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
+            Ok(()) => Ok(true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// Hashes `password` with Argon2id and writes a `username:phc_hash` entry to `path`.
+pub fn provision_local_credential(path: &PathBuf, username: &str, password: &str) -> Result<(), String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| format!("Failed to hash password: {}", e))?
+        .to_string();
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| format!("Failed to create '{}': {}", parent.display(), e))?;
+    }
+
+    let mut entries: Vec<String> = read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with(&format!("{}:", username)))
+        .map(|line| line.to_string())
+        .collect();
+    entries.push(format!("{}:{}", username, hash));
+
+    write(path, entries.join("\n") + "\n")
+        .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))?;
+
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to set permissions on '{}': {}", path.display(), e))?;
+
+    log_info(&format!("Provisioned local credential for '{}' in '{}'", username, path.display()));
+    Ok(())
+}
+
+fn backend_for(config: &Config) -> Box<dyn AuthBackend> {
+    match config.auth_backend {
+        AuthBackendKind::Pam => Box::new(PamBackend),
+        AuthBackendKind::File => Box::new(FileBackend::new(config.auth_file_path.clone())),
+    }
+}
+
 pub struct AuthState {
     pub last_authenticated: Option<Instant>,
     pub timeout: Duration,
@@ -16,15 +146,21 @@ pub struct AuthState {
     pub failed_attempts: u32,
     pub lockout_time: Option<Instant>,
     pub roles: Vec<String>,
+    /// When false (`-d`/`--no-persist`), don't cache the auth timestamp.
+    pub persist: bool,
 }
 
 impl AuthState {
-    pub fn new(timeout: Duration, username: String, groups: Vec<String>) -> Self {
+    pub fn new(timeout: Duration, username: String, groups: Vec<String>, config: &Config) -> Self {
+        Self::with_persist(timeout, username, groups, true, config)
+    }
+
+    pub fn with_persist(timeout: Duration, username: String, groups: Vec<String>, persist: bool, config: &Config) -> Self {
         let last_authenticated = load_last_auth(&username);
-        let roles = get_roles_for_user(&username);
+        let roles = config.roles_for_user(&username);
         log_debug(&format!(
-            "Initializing AuthState for user '{}'. Timeout: {:?}, Groups: {:?}, Roles: {:?}",
-            username, timeout, groups, roles
+            "Initializing AuthState for user '{}'. Timeout: {:?}, Groups: {:?}, Roles: {:?}, Persist: {}",
+            username, timeout, groups, roles, persist
         ));
         AuthState {
             last_authenticated,
@@ -34,6 +170,7 @@ impl AuthState {
             roles,
             failed_attempts: 0,
             lockout_time: None,
+            persist,
         }
     }
 
@@ -45,7 +182,11 @@ impl AuthState {
 
     pub fn update_last_authenticated(&mut self) {
         self.last_authenticated = Some(Instant::now());
-        store_auth_timestamp(&self.username);
+        if self.persist {
+            store_auth_timestamp(&self.username);
+        } else {
+            log_debug(&format!("Not persisting auth timestamp for '{}' (--no-persist)", self.username));
+        }
         self.failed_attempts = 0;
     }
 
@@ -119,8 +260,28 @@ impl CustomConversation {
     }
 }
 
+/// Returns the caller's controlling terminal device (e.g. `/dev/pts/3`), if any.
+fn controlling_tty() -> Option<String> {
+    unsafe {
+        let ptr = nix::libc::ttyname(0);
+        if ptr.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+/// Turns a tty device path into a string safe to embed in a filename.
+fn sanitize_tty(tty: &str) -> String {
+    tty.trim_start_matches("/dev/").replace('/', "-")
+}
+
+/// Timestamp cache path, scoped per controlling terminal (mirrors sudo/doas).
 fn auth_timestamp_path(user: &str) -> PathBuf {
-    PathBuf::from(format!("/run/elev/auth-{}.ts", user))
+    let tty = controlling_tty()
+        .map(|t| sanitize_tty(&t))
+        .unwrap_or_else(|| "notty".to_string());
+    PathBuf::from(format!("/run/elev/auth-{}-{}.ts", user, tty))
 }
 
 fn load_last_auth(user: &str) -> Option<Instant> {
@@ -147,15 +308,6 @@ fn store_auth_timestamp(user: &str) {
     }
 }
 
-fn get_roles_for_user(username: &str) -> Vec<String> {
-    // TODO: replace with real lookup
-    match username {
-        "admin" => vec!["admin".into(), "developer".into()],
-        "user1" => vec!["user".into()],
-        _ => vec![],
-    }
-}
-
 pub fn verify_password(user: &str, auth_state: &mut AuthState, config: &Config) -> bool {
     log_debug(&format!("Starting password verification for user '{}'", user));
 
@@ -170,46 +322,27 @@ pub fn verify_password(user: &str, auth_state: &mut AuthState, config: &Config)
 
     const MAX_ATTEMPTS: u32 = 3;
     let mut attempts = 0;
+    let backend = backend_for(config);
 
     while attempts < MAX_ATTEMPTS {
-        // Initialize a new PAM context (uses /etc/pam.d/elev)
-        let mut ctx = match Context::new("elev", Some(user), CustomConversation {
-            prompt: format!("[ elev ] Please enter password for {}: ", user),
-        }) {
-            Ok(c) => c,
-            Err(e) => {
-                log_error(&format!("PAM init failed: {}", e));
-                return false;
+        match backend.authenticate(user) {
+            Ok(true) => {
+                auth_state.update_last_authenticated();
+                log_info(&format!("Successful login for user: {}", user));
+                return true;
             }
-        };
-
-        // Authenticate (prompts for password via Conversation)
-        if let Err(e) = ctx.authenticate(Flag::NONE) {
-            log_error(&format!("PAM authentication failed: {}", e));
-            attempts += 1;
-            auth_state.increment_failed_attempts();
-            eprintln!("Failed login attempt #{}", attempts);
-            if attempts < MAX_ATTEMPTS {
-                eprintln!("Incorrect password. {} attempt(s) left.", MAX_ATTEMPTS - attempts);
+            result => {
+                if let Err(e) = result {
+                    log_error(&format!("Authentication failed for '{}': {}", user, e));
+                }
+                attempts += 1;
+                auth_state.increment_failed_attempts();
+                eprintln!("Failed login attempt #{}", attempts);
+                if attempts < MAX_ATTEMPTS {
+                    eprintln!("Incorrect password. {} attempt(s) left.", MAX_ATTEMPTS - attempts);
+                }
             }
-            continue;
-        }
-
-        // Account management checks (e.g., expired, locked)
-        if let Err(e) = ctx.acct_mgmt(Flag::NONE) {
-            eprintln!("Account validation failed: {}", e);
-            return false;
         }
-
-        // Optional: open a session
-        let _ = ctx.open_session(Flag::NONE);
-
-        // Success: update state and return
-        auth_state.update_last_authenticated();
-        log_info(&format!("Successful login for user: {}", user));
-        // Optional: close session if desired
-        // let _ = ctx.close_session(Flag::NONE);
-        return true;
     }
 
     eprintln!("User '{}' failed to authenticate after {} attempt(s).", user, MAX_ATTEMPTS);
@@ -1,8 +1,10 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use regex::Regex;
 use std::time::Duration;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::logs::{log_info, log_warn, log_error};
 
 #[derive(Clone, Debug)]
@@ -15,6 +17,45 @@ pub struct Rule {
     pub allowed_roles: Option<Vec<String>>,
     pub deny: bool,
     pub time_range: Option<(chrono::NaiveTime, chrono::NaiveTime)>,
+    /// Skip the `verify_password` step when this rule grants access.
+    pub nopass: bool,
+    /// Cache the auth timestamp for this rule's commands even with `--no-persist`.
+    pub persist: bool,
+}
+
+/// The outcome of `Config::is_permitted`: allow/deny plus the matched rule's
+/// priority, `nopass`, and `persist` flags.
+#[derive(Clone, Copy, Debug)]
+pub struct PermissionDecision {
+    pub allowed: bool,
+    pub priority: Option<u8>,
+    pub nopass: bool,
+    pub persist: bool,
+}
+
+impl PermissionDecision {
+    fn allowed(priority: Option<u8>, nopass: bool, persist: bool) -> Self {
+        PermissionDecision { allowed: true, priority, nopass, persist }
+    }
+
+    fn denied(priority: Option<u8>) -> Self {
+        PermissionDecision { allowed: false, priority, nopass: false, persist: false }
+    }
+}
+
+/// A role definition: its direct members, parent roles, and granted permissions.
+#[derive(Clone, Debug, Default)]
+pub struct RoleDef {
+    pub members: Vec<String>,
+    pub parents: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+/// Which `AuthBackend` `verify_password` should authenticate against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthBackendKind {
+    Pam,
+    File,
 }
 
 #[derive(Debug)]
@@ -22,74 +63,335 @@ pub struct Config {
     pub rules: Vec<Rule>,
     pub timeout: Duration,
     pub password_required: bool,
-    pub roles: HashMap<String, Vec<String>>,
+    pub roles: HashMap<String, RoleDef>,
+    pub auth_backend: AuthBackendKind,
+    pub auth_file_path: String,
+    pub use_pty: bool,
+    pub syslog_facility: String,
+    pub syslog_tag: String,
+    /// Whether `--chdir` may be used at all, in login or normal mode.
+    pub allow_chdir: bool,
+}
+
+/// A rejected config entry, with where it came from and why.
+#[derive(Debug)]
+pub struct RejectedEntry {
+    pub path: PathBuf,
+    pub line: usize,
+    pub content: String,
+    pub reason: String,
+}
+
+impl fmt::Display for RejectedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {} (`{}`)", self.path.display(), self.line, self.reason, self.content)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    IncludeCycle(PathBuf),
+    Invalid(Vec<RejectedEntry>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "I/O error loading configuration: {}", e),
+            ConfigError::IncludeCycle(path) => {
+                write!(f, "include cycle detected: '{}' was already included", path.display())
+            }
+            ConfigError::Invalid(entries) => {
+                writeln!(f, "{} configuration entr{} rejected:", entries.len(), if entries.len() == 1 { "y" } else { "ies" })?;
+                for entry in entries {
+                    writeln!(f, "  {}", entry)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+/// A config line plus where it came from, after include expansion.
+struct LineEntry {
+    path: PathBuf,
+    line: usize,
+    content: String,
+}
+
+/// Recursively expands `include`/`includedir` directives into `out`; a path
+/// already in `visited` is an include cycle.
+fn expand_includes(
+    filename: &Path,
+    visited: &mut HashSet<PathBuf>,
+    out: &mut Vec<LineEntry>,
+) -> Result<(), ConfigError> {
+    let canonical = filename.canonicalize().unwrap_or_else(|_| filename.to_path_buf());
+    if !visited.insert(canonical.clone()) {
+        return Err(ConfigError::IncludeCycle(canonical));
+    }
+
+    let file = File::open(filename)?;
+    let reader = BufReader::new(file);
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = idx + 1;
+        let content = line?.trim().to_string();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        if let Some(inc) = content.strip_prefix("include ") {
+            expand_includes(Path::new(inc.trim()), visited, out)?;
+            continue;
+        }
+
+        if let Some(dir) = content.strip_prefix("includedir ") {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(dir.trim())?
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect();
+            entries.sort();
+            for entry in entries {
+                expand_includes(&entry, visited, out)?;
+            }
+            continue;
+        }
+
+        out.push(LineEntry { path: filename.to_path_buf(), line: line_no, content });
+    }
+
+    Ok(())
 }
 
 impl Config {
-    pub fn load(filename: &str) -> Result<Self, std::io::Error> {
+    pub fn load(filename: &str) -> Result<Self, ConfigError> {
         log_info(&format!("Loading configuration from file: {}", filename));  // Log configuration load
-        let file = File::open(filename)?;
-        let reader = BufReader::new(file);
+
+        let mut lines = Vec::new();
+        let mut visited = HashSet::new();
+        expand_includes(Path::new(filename), &mut visited, &mut lines)?;
+
         let mut rules = Vec::new();
         let mut timeout = Duration::from_secs(60);
         let mut password_required = true;
-        let mut roles: HashMap<String, Vec<String>> = HashMap::new();
-        let mut raw_lines = Vec::new();
+        let mut auth_backend = AuthBackendKind::Pam;
+        let mut auth_file_path = String::from("/etc/elev/shadow");
+        let mut use_pty = false;
+        let mut syslog_facility = String::from("authpriv");
+        let mut syslog_tag = String::from("elev");
+        let mut allow_chdir = false;
+        let mut roles: HashMap<String, RoleDef> = HashMap::new();
+        let mut rejects: Vec<RejectedEntry> = Vec::new();
 
-        for line in reader.lines() {
-            let line = line?.trim().to_string();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
-        
-            if let Some(role_def) = line.strip_prefix("role ") {
-                let mut parts = role_def.splitn(2, ' ');
-                if let Some(role_name) = parts.next() {
-                    if let Some(users_str) = parts.next() {
-                        let users: Vec<String> = users_str
-                            .split(',')
-                            .map(|s| s.trim().to_string())
-                            .collect();
-                        roles.insert(role_name.to_string(), users.clone());
-                        log_info(&format!("Defined role '{}' with members {:?}", role_name, users));
+        // First pass: role definitions, so rules parsed in the second pass
+        // can validate `roles <csv>` references regardless of file order.
+        for entry in &lines {
+            if let Some(role_def) = entry.content.strip_prefix("role ") {
+                let tokens: Vec<&str> = role_def.split_whitespace().collect();
+                let Some(&role_name) = tokens.first() else {
+                    rejects.push(RejectedEntry {
+                        path: entry.path.clone(),
+                        line: entry.line,
+                        content: entry.content.clone(),
+                        reason: "role directive is missing a name".to_string(),
+                    });
+                    continue;
+                };
+
+                let mut members = Vec::new();
+                let mut parents = Vec::new();
+                let mut permissions = Vec::new();
+                let mut i = 1;
+                if i < tokens.len() && tokens[i] != "parents" && tokens[i] != "permissions" {
+                    members = tokens[i]
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                    i += 1;
+                }
+                while i < tokens.len() {
+                    match tokens[i] {
+                        "parents" if i + 1 < tokens.len() => {
+                            parents = tokens[i + 1]
+                                .split(',')
+                                .map(|s| s.trim().to_string())
+                                .filter(|s| !s.is_empty())
+                                .collect();
+                            i += 2;
+                        }
+                        "permissions" if i + 1 < tokens.len() => {
+                            permissions = tokens[i + 1]
+                                .split(',')
+                                .map(|s| s.trim().to_string())
+                                .filter(|s| !s.is_empty())
+                                .collect();
+                            i += 2;
+                        }
+                        _ => { i += 1; }
                     }
                 }
-            } else {
-                raw_lines.push(line);
+                log_info(&format!(
+                    "Defined role '{}' with members {:?}, parents {:?}, permissions {:?}",
+                    role_name, members, parents, permissions
+                ));
+                roles.insert(role_name.to_string(), RoleDef { members, parents, permissions });
             }
         }
-        
-        // Second pass: parse rules and global settings
-        for line in &raw_lines {
-            if let Some(rule) = parse_rule(&line, &roles) {
-                rules.push(rule);
-            }
-        
-            if let Some(timeout_str) = line.strip_prefix("timeout ") {
-                if let Ok(timeout_value) = timeout_str.trim().parse::<u64>() {
-                    timeout = Duration::from_secs(timeout_value);
-                    log_info(&format!("Loaded timeout value from config: {} seconds", timeout_value));
+
+        // Second pass: rules and global settings.
+        for entry in &lines {
+            let first_token = entry.content.split_whitespace().next().unwrap_or("");
+            match first_token {
+                "role" => continue, // handled above
+                "allow" | "deny" => match parse_rule(&entry.content, &roles) {
+                    Ok(rule) => rules.push(rule),
+                    Err(reason) => rejects.push(RejectedEntry {
+                        path: entry.path.clone(),
+                        line: entry.line,
+                        content: entry.content.clone(),
+                        reason,
+                    }),
+                },
+                "timeout" => {
+                    let value_str = entry.content["timeout".len()..].trim();
+                    match value_str.parse::<u64>() {
+                        Ok(value) => {
+                            timeout = Duration::from_secs(value);
+                            log_info(&format!("Loaded timeout value from config: {} seconds", value));
+                        }
+                        Err(e) => rejects.push(RejectedEntry {
+                            path: entry.path.clone(),
+                            line: entry.line,
+                            content: entry.content.clone(),
+                            reason: format!("invalid timeout value: {}", e),
+                        }),
+                    }
                 }
-            }
-        
-            if let Some(password_str) = line.strip_prefix("password_required ") {
-                if let Ok(pass_req) = password_str.trim().parse::<bool>() {
-                    password_required = pass_req;
-                    log_info(&format!("Loaded password_required value from config: {}", password_required));
+                "password_required" => {
+                    let value_str = entry.content["password_required".len()..].trim();
+                    match value_str.parse::<bool>() {
+                        Ok(value) => {
+                            password_required = value;
+                            log_info(&format!("Loaded password_required value from config: {}", password_required));
+                        }
+                        Err(e) => rejects.push(RejectedEntry {
+                            path: entry.path.clone(),
+                            line: entry.line,
+                            content: entry.content.clone(),
+                            reason: format!("invalid password_required value: {}", e),
+                        }),
+                    }
                 }
+                "auth_backend" => {
+                    let value_str = entry.content["auth_backend".len()..].trim();
+                    match value_str {
+                        "pam" => auth_backend = AuthBackendKind::Pam,
+                        "file" => auth_backend = AuthBackendKind::File,
+                        other => rejects.push(RejectedEntry {
+                            path: entry.path.clone(),
+                            line: entry.line,
+                            content: entry.content.clone(),
+                            reason: format!("unknown auth_backend '{}'", other),
+                        }),
+                    }
+                }
+                "auth_file_path" => {
+                    auth_file_path = entry.content["auth_file_path".len()..].trim().to_string();
+                    log_info(&format!("Loaded auth_file_path value from config: {}", auth_file_path));
+                }
+                "pty" => {
+                    let value_str = entry.content["pty".len()..].trim();
+                    match value_str.parse::<bool>() {
+                        Ok(value) => {
+                            use_pty = value;
+                            log_info(&format!("Loaded pty value from config: {}", use_pty));
+                        }
+                        Err(e) => rejects.push(RejectedEntry {
+                            path: entry.path.clone(),
+                            line: entry.line,
+                            content: entry.content.clone(),
+                            reason: format!("invalid pty value: {}", e),
+                        }),
+                    }
+                }
+                "syslog_facility" => {
+                    syslog_facility = entry.content["syslog_facility".len()..].trim().to_string();
+                    log_info(&format!("Loaded syslog_facility value from config: {}", syslog_facility));
+                }
+                "syslog_tag" => {
+                    syslog_tag = entry.content["syslog_tag".len()..].trim().to_string();
+                    log_info(&format!("Loaded syslog_tag value from config: {}", syslog_tag));
+                }
+                "chdir" => {
+                    let value_str = entry.content["chdir".len()..].trim();
+                    match value_str.parse::<bool>() {
+                        Ok(value) => {
+                            allow_chdir = value;
+                            log_info(&format!("Loaded chdir value from config: {}", allow_chdir));
+                        }
+                        Err(e) => rejects.push(RejectedEntry {
+                            path: entry.path.clone(),
+                            line: entry.line,
+                            content: entry.content.clone(),
+                            reason: format!("invalid chdir value: {}", e),
+                        }),
+                    }
+                }
+                other => rejects.push(RejectedEntry {
+                    path: entry.path.clone(),
+                    line: entry.line,
+                    content: entry.content.clone(),
+                    reason: format!("unrecognized directive '{}'", other),
+                }),
             }
         }
 
+        if !rejects.is_empty() {
+            return Err(ConfigError::Invalid(rejects));
+        }
+
         log_info(&format!("Loaded {} rules from configuration", rules.len()));  // Log the number of rules loaded
 
         Ok(Config {
             rules,
             timeout,
             password_required,
+            auth_backend,
+            auth_file_path,
+            use_pty,
+            syslog_facility,
+            syslog_tag,
+            allow_chdir,
             roles,
         })
     }
 
+    /// Returns the names of every role `username` is a direct member of.
+    pub fn roles_for_user(&self, username: &str) -> Vec<String> {
+        self.roles
+            .iter()
+            .filter_map(|(role, def)| {
+                if def.members.iter().any(|m| m == username) {
+                    Some(role.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
     pub fn is_permitted(
         &self,
         user: &str,
@@ -97,7 +399,7 @@ impl Config {
         target_user: &str,
         command: &str,
         user_roles: &[String],
-    ) -> bool {
+    ) -> PermissionDecision {
         log_info(&format!("Checking permission for user '{}' to run command '{}'", user, command));  // Log permission check
         let mut rules = self.rules.clone();
         rules.sort_by(|a, b| b.priority.cmp(&a.priority));
@@ -105,22 +407,100 @@ impl Config {
         for rule in &rules {
             if rule.deny && rule.matches(user, groups, target_user, command, user_roles) {
                 log_warn(&format!("Permission denied for user '{}' to run command '{}'", user, command));  // Log deny rule match
-                return false;
+                return PermissionDecision::denied(Some(rule.priority));
             }
         }
 
         for rule in &rules {
             if !rule.deny && rule.matches(user, groups, target_user, command, user_roles) {
                 log_info(&format!("Permission granted for user '{}' to run command '{}'", user, command));  // Log allow rule match
-                return true;
+                return PermissionDecision::allowed(Some(rule.priority), rule.nopass, rule.persist);
+            }
+        }
+
+        // No explicit rule matched; fall back to permissions granted by the
+        // user's roles and everything they transitively inherit.
+        let effective = self.resolve_effective_permissions(user_roles);
+        for pattern in &effective {
+            if command_matches_pattern(pattern, command) {
+                log_info(&format!(
+                    "Permission granted for user '{}' to run command '{}' via role permission '{}'",
+                    user, command, pattern
+                ));
+                return PermissionDecision::allowed(None, false, false);
             }
         }
 
         log_error(&format!("Permission check failed for user '{}' to run command '{}'", user, command));  // Log permission failure
-        false
+        PermissionDecision::denied(None)
+    }
+
+    /// Union of permission patterns granted by `role_names` and their parents.
+    pub fn resolve_effective_permissions(&self, role_names: &[String]) -> HashSet<String> {
+        let mut patterns = HashSet::new();
+        let mut stack = Vec::new();
+        for name in role_names {
+            self.collect_role_permissions(name, &mut stack, &mut patterns);
+        }
+        patterns
+    }
+
+    fn collect_role_permissions(
+        &self,
+        role_name: &str,
+        stack: &mut Vec<String>,
+        patterns: &mut HashSet<String>,
+    ) {
+        if stack.iter().any(|r| r == role_name) {
+            log_warn(&format!(
+                "Role inheritance cycle detected at '{}', skipping",
+                role_name
+            ));
+            return;
+        }
+        let role = match self.roles.get(role_name) {
+            Some(role) => role,
+            None => return,
+        };
+
+        stack.push(role_name.to_string());
+        patterns.extend(role.permissions.iter().cloned());
+        for parent in &role.parents {
+            self.collect_role_permissions(parent, stack, patterns);
+        }
+        stack.pop();
     }
 }
 
+/// Matches a command path against a role permission pattern, segment by
+/// segment; `*` matches one segment, `**` (or a trailing `*`) matches the rest.
+fn command_matches_pattern(pattern: &str, command: &str) -> bool {
+    let pat_segs: Vec<&str> = pattern
+        .split(|c| c == '/' || c == '.')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let cmd_segs: Vec<&str> = command
+        .split(|c| c == '/' || c == '.')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let mut ci = 0;
+    for (pi, pseg) in pat_segs.iter().enumerate() {
+        let is_last = pi == pat_segs.len() - 1;
+        if *pseg == "**" || (is_last && *pseg == "*") {
+            return true;
+        }
+        if ci >= cmd_segs.len() {
+            return false;
+        }
+        if *pseg != "*" && *pseg != cmd_segs[ci] {
+            return false;
+        }
+        ci += 1;
+    }
+    ci == cmd_segs.len()
+}
+
 impl Rule {
     fn matches(
         &self,
@@ -140,7 +520,7 @@ impl Rule {
             _ => true,
         };
 
-        if !user_ok && !group_ok {
+        if !user_ok || !group_ok {
             return false;
         }
         
@@ -185,10 +565,10 @@ fn wildcard_to_regex(pattern: &str) -> String {
     regex
 }
 
-fn parse_rule(line: &str, roles_map: &HashMap<String, Vec<String>>) -> Option<Rule> {
+fn parse_rule(line: &str, roles_map: &HashMap<String, RoleDef>) -> Result<Rule, String> {
     let tokens: Vec<&str> = line.split_whitespace().collect();
     if tokens.is_empty() {
-        return None;
+        return Err("empty rule".to_string());
     }
 
     let mut deny = false;
@@ -196,7 +576,7 @@ fn parse_rule(line: &str, roles_map: &HashMap<String, Vec<String>>) -> Option<Ru
     match tokens[i] {
         "deny" => { deny = true; i += 1; }
         "allow" => { i += 1; }
-        _ => return None,
+        other => return Err(format!("rule must start with 'allow' or 'deny', found '{}'", other)),
     }
 
     let mut user = None;
@@ -209,14 +589,21 @@ fn parse_rule(line: &str, roles_map: &HashMap<String, Vec<String>>) -> Option<Ru
             user = Some(t.to_string());
         }
         i += 1;
+    } else {
+        return Err("rule is missing a user or :group subject".to_string());
     }
 
     let mut as_user = None;
     let mut command_pat = None;
     let mut priority = 0;
     let mut allowed_roles = None;
+    let mut time_range = None;
+    let mut nopass = false;
+    let mut persist = false;
     while i < tokens.len() {
         match tokens[i] {
+            "nopass" => { nopass = true; i += 1; }
+            "persist" => { persist = true; i += 1; }
             "as" if i + 1 < tokens.len() => {
                 as_user = Some(tokens[i + 1].to_string());
                 i += 2;
@@ -226,46 +613,145 @@ fn parse_rule(line: &str, roles_map: &HashMap<String, Vec<String>>) -> Option<Ru
                 i += 2;
             }
             "priority" if i + 1 < tokens.len() => {
-                priority = tokens[i + 1].parse().unwrap_or(0);
+                priority = tokens[i + 1]
+                    .parse()
+                    .map_err(|e| format!("invalid priority '{}': {}", tokens[i + 1], e))?;
                 i += 2;
             }
             "roles" if i + 1 < tokens.len() => {
                 let parsed_roles: Vec<String> = tokens[i + 1].split(',').map(|s| s.to_string()).collect();
-            
+
                 // Validate roles exist in the map
                 for role in &parsed_roles {
                     if !roles_map.contains_key(role) {
                         log_warn(&format!("Rule references undefined role: '{}'", role));
                     }
                 }
-            
+
                 allowed_roles = Some(parsed_roles);
                 i += 2;
             }
             "timing" if i + 1 < tokens.len() => {
                 let time_range_str = tokens[i + 1];
                 let times: Vec<&str> = time_range_str.split('-').collect();
-                if times.len() == 2 {
-                    let start_time = chrono::NaiveTime::parse_from_str(times[0], "%H:%M").unwrap();
-                    let end_time = chrono::NaiveTime::parse_from_str(times[1], "%H:%M").unwrap();
-                    time_range = Some((start_time, end_time));
+                if times.len() != 2 {
+                    return Err(format!("invalid timing range '{}', expected HH:MM-HH:MM", time_range_str));
                 }
+                let start_time = chrono::NaiveTime::parse_from_str(times[0], "%H:%M")
+                    .map_err(|e| format!("invalid timing start '{}': {}", times[0], e))?;
+                let end_time = chrono::NaiveTime::parse_from_str(times[1], "%H:%M")
+                    .map_err(|e| format!("invalid timing end '{}': {}", times[1], e))?;
+                time_range = Some((start_time, end_time));
                 i += 2;
             }
-            _ => { i += 1; }
+            other => return Err(format!("unrecognized rule keyword '{}'", other)),
         }
     }
 
-    let cmd_regex = command_pat.map(|pat| {
-        let re_str = if pat.contains('*') || pat.contains('?') {
-            wildcard_to_regex(&pat)
-        } else if pat == "*" {
-            String::from("^.*$")
-        } else {
-            format!("^{pat}$")
+    let cmd_regex = match command_pat {
+        Some(pat) => {
+            let re_str = if pat.contains('*') || pat.contains('?') {
+                wildcard_to_regex(&pat)
+            } else {
+                format!("^{pat}$")
+            };
+            Some(Regex::new(&re_str).map_err(|e| format!("invalid cmd pattern '{}': {}", pat, e))?)
+        }
+        None => None,
+    };
+
+    Ok(Rule { user, group, as_user, cmd_regex, priority, allowed_roles, deny, time_range, nopass, persist })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_matches_pattern_exact() {
+        assert!(command_matches_pattern("systemctl.restart.nginx", "systemctl.restart.nginx"));
+        assert!(!command_matches_pattern("systemctl.restart.nginx", "systemctl.restart.apache"));
+    }
+
+    #[test]
+    fn command_matches_pattern_single_segment_wildcard() {
+        assert!(command_matches_pattern("systemctl.restart.*", "systemctl.restart.nginx"));
+        assert!(!command_matches_pattern("systemctl.*.nginx", "systemctl.restart.extra.nginx"));
+        assert!(!command_matches_pattern("systemctl.*.nginx", "systemctl.nginx"));
+    }
+
+    #[test]
+    fn command_matches_pattern_double_star_matches_remainder() {
+        assert!(command_matches_pattern("systemctl.**", "systemctl.restart.nginx"));
+        assert!(command_matches_pattern("systemctl.**", "systemctl"));
+    }
+
+    #[test]
+    fn command_matches_pattern_requires_all_segments_consumed() {
+        assert!(!command_matches_pattern("systemctl.restart", "systemctl.restart.nginx"));
+    }
+
+    fn rule(line: &str) -> Rule {
+        parse_rule(line, &HashMap::new()).expect("valid rule")
+    }
+
+    #[test]
+    fn rule_matches_enforces_user_subject() {
+        let r = rule("allow alice cmd whoami");
+        assert!(r.matches("alice", &[], "root", "whoami", &[]));
+        assert!(!r.matches("bob", &[], "root", "whoami", &[]));
+    }
+
+    #[test]
+    fn rule_matches_enforces_group_subject() {
+        let r = rule("allow :wheel cmd whoami");
+        assert!(r.matches("alice", &["wheel".to_string()], "root", "whoami", &[]));
+        assert!(!r.matches("alice", &["users".to_string()], "root", "whoami", &[]));
+    }
+
+    #[test]
+    fn rule_matches_as_user_restriction() {
+        let r = rule("allow alice as deploy cmd whoami");
+        assert!(r.matches("alice", &[], "deploy", "whoami", &[]));
+        assert!(!r.matches("alice", &[], "root", "whoami", &[]));
+    }
+
+    #[test]
+    fn resolve_effective_permissions_breaks_inheritance_cycle() {
+        let mut roles = HashMap::new();
+        roles.insert("a".to_string(), RoleDef { members: vec![], parents: vec!["b".to_string()], permissions: vec!["a.perm".to_string()] });
+        roles.insert("b".to_string(), RoleDef { members: vec![], parents: vec!["a".to_string()], permissions: vec!["b.perm".to_string()] });
+        let config = Config {
+            rules: vec![],
+            timeout: Duration::from_secs(60),
+            password_required: true,
+            roles,
+            auth_backend: AuthBackendKind::Pam,
+            auth_file_path: String::new(),
+            use_pty: false,
+            syslog_facility: String::new(),
+            syslog_tag: String::new(),
+            allow_chdir: false,
         };
-        Regex::new(&re_str).unwrap_or_else(|_| Regex::new("^$").unwrap())
-    });
 
-    Some(Rule { user, group, as_user, cmd_regex, priority, allowed_roles, deny })
+        let perms = config.resolve_effective_permissions(&["a".to_string()]);
+        assert_eq!(perms, HashSet::from(["a.perm".to_string(), "b.perm".to_string()]));
+    }
+
+    #[test]
+    fn expand_includes_rejects_cycle() {
+        let dir = std::env::temp_dir().join(format!("elev-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.conf");
+        let b = dir.join("b.conf");
+        std::fs::write(&a, format!("include {}\n", b.display())).unwrap();
+        std::fs::write(&b, format!("include {}\n", a.display())).unwrap();
+
+        let mut visited = HashSet::new();
+        let mut out = Vec::new();
+        let result = expand_includes(&a, &mut visited, &mut out);
+
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(matches!(result, Err(ConfigError::IncludeCycle(_))));
+    }
 }
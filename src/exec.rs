@@ -1,44 +1,154 @@
-use nix::unistd::{setuid, User, getgroups}; // Add this to get groups for a user
+use nix::libc;
+use nix::unistd::{setuid, setgid, setgroups, getuid, getgid, User, Gid};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus};
 use crate::config::Config;
 use crate::auth::AuthState;
 use crate::logs::{log_info, log_warn, log_error};
+use crate::util::resolve_user_gids;
 
+/// Resolves the supplementary `Gid`s for `username` via `getgrouplist(3)`.
+fn resolve_supplementary_gids(username: &str) -> Vec<Gid> {
+    match User::from_name(username) {
+        Ok(Some(user)) => resolve_user_gids(username, user.gid),
+        Ok(None) => {
+            log_warn(&format!("No password entry found for '{}'", username));
+            Vec::new()
+        }
+        Err(e) => {
+            log_warn(&format!("Failed to look up user '{}': {}", username, e));
+            Vec::new()
+        }
+    }
+}
+
+/// Drops privileges from root to `target_user`: groups, then gid, then uid.
 pub fn switch_user(target_user: &str) -> Result<(), String> {
-    match User::from_name(target_user).map_err(|e| e.to_string())? {
-        Some(user_struct) => {
-            log_info(&format!("Switching to user '{}'", target_user));  // Log the user switch action
-            setuid(user_struct.uid).map_err(|e| e.to_string())  // Switch user
-        },
-        None => {
-            log_error(&format!("User '{}' not found", target_user));  // Log error if user not found
-            Err(format!("User '{}' not found", target_user))
-        },
+    let user_struct = User::from_name(target_user)
+        .map_err(|e| format!("Failed to look up user '{}': {}", target_user, e))?
+        .ok_or_else(|| {
+            log_error(&format!("User '{}' not found", target_user));
+            format!("User '{}' not found", target_user)
+        })?;
+
+    log_info(&format!("Switching to user '{}'", target_user));
+
+    let supplementary = resolve_supplementary_gids(target_user);
+    setgroups(&supplementary)
+        .map_err(|e| format!("setgroups failed while switching to '{}': {}", target_user, e))?;
+
+    setgid(user_struct.gid)
+        .map_err(|e| format!("setgid failed while switching to '{}': {}", target_user, e))?;
+
+    setuid(user_struct.uid)
+        .map_err(|e| format!("setuid failed while switching to '{}': {}", target_user, e))?;
+
+    if getuid() != user_struct.uid || getgid() != user_struct.gid {
+        return Err(format!(
+            "Privilege drop to '{}' did not take effect (uid/gid mismatch after switch)",
+            target_user
+        ));
     }
+
+    Ok(())
+}
+
+/// Confirms `dir` exists and is accessible; call *after* `switch_user`.
+fn validate_chdir(dir: &Path) -> Result<(), String> {
+    use nix::unistd::{access, AccessFlags};
+
+    if !dir.is_dir() {
+        return Err(format!("'{}' is not a directory", dir.display()));
+    }
+    access(dir, AccessFlags::X_OK)
+        .map_err(|e| format!("'{}' is not accessible: {}", dir.display(), e))
 }
 
-pub fn check_group_permission(user: &str, required_group: &str) -> bool {
-    if let Ok(groups) = getgroups() {
-        for group in groups {
-            let group_name = match group.to_group_name() {
-                Ok(name) => name,
-                Err(_) => continue, // Skip if group name retrieval fails
-            };
-            if group_name == required_group {
-                return true; // User is in the required group
+/// Resolves `target_user`'s login shell, falling back to `/bin/sh` if unset.
+pub fn login_shell_path(target_user: &str) -> Result<PathBuf, std::io::Error> {
+    let user_entry = User::from_name(target_user)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, format!("User '{}' not found", target_user))
+        })?;
+
+    Ok(if user_entry.shell.as_os_str().is_empty() {
+        PathBuf::from("/bin/sh")
+    } else {
+        user_entry.shell
+    })
+}
+
+/// Execs `target_user`'s login shell in place of the current process, for
+/// `elev`-as-`su` style interactive escalation when no command is given.
+pub fn exec_login_shell(target_user: &str, chdir: Option<&Path>) -> std::io::Error {
+    if let Err(e) = switch_user(target_user) {
+        return std::io::Error::new(std::io::ErrorKind::PermissionDenied, e);
+    }
+
+    let user_entry = match User::from_name(target_user) {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            return std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("User '{}' not found", target_user),
+            )
+        }
+        Err(e) => return std::io::Error::new(std::io::ErrorKind::Other, e.to_string()),
+    };
+
+    let work_dir = match chdir {
+        Some(dir) => {
+            if let Err(e) = validate_chdir(dir) {
+                return std::io::Error::new(std::io::ErrorKind::NotFound, e);
             }
+            dir.to_path_buf()
         }
+        None => user_entry.dir.clone(),
+    };
+
+    let shell_path = if user_entry.shell.as_os_str().is_empty() {
+        PathBuf::from("/bin/sh")
+    } else {
+        user_entry.shell.clone()
+    };
+    let shell_name = shell_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("sh");
+
+    log_info(&format!(
+        "Launching login shell '{}' for user '{}' in '{}'",
+        shell_path.display(),
+        target_user,
+        work_dir.display()
+    ));
+
+    let mut shell = Command::new(&shell_path);
+    shell.arg0(format!("-{}", shell_name));
+    shell.env("HOME", &user_entry.dir);
+    shell.env("SHELL", &shell_path);
+    shell.env("USER", target_user);
+    shell.env("LOGNAME", target_user);
+    shell.env("PATH", "/usr/bin:/bin:/usr/sbin:/sbin");
+    if let Ok(term) = std::env::var("TERM") {
+        shell.env("TERM", term);
     }
-    false // User is not in the required group
+    shell.current_dir(&work_dir);
+
+    shell.exec()
 }
 
 pub fn run_command(
-    config: &Config,
+    _config: &Config,
     auth_state: &mut AuthState,
     target_user: &str,
     cmd: &str,
     args: &[&str],
-    required_group: &str, // Adding the group check
+    use_pty: bool,
+    chdir: Option<&Path>,
 ) -> Result<ExitStatus, std::io::Error> {
 
     // Handle timeout check
@@ -53,16 +163,10 @@ pub fn run_command(
         ));
     }
 
-    // Check if the user is permitted based on their group
-    if !check_group_permission(auth_state.username.as_str(), required_group) {
-        log_warn(&format!(
-            "User '{}' does not have permission to execute commands as '{}'",
-            auth_state.username, target_user
-        ));
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::PermissionDenied,
-            "User is not authorized to execute this command",
-        ));
+    log_info(&format!("Running command: '{} {}'", cmd, args.join(" ")));
+
+    if use_pty {
+        return run_command_pty(target_user, cmd, args, chdir);
     }
 
     // Switch user before running the command
@@ -74,6 +178,13 @@ pub fn run_command(
         ));
     }
 
+    if let Some(dir) = chdir {
+        if let Err(e) = validate_chdir(dir) {
+            log_error(&e);
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, e));
+        }
+    }
+
     // Now execute the command with all arguments
     let mut command = Command::new(cmd);
     command.args(args);  // Pass the arguments here
@@ -82,9 +193,169 @@ pub fn run_command(
     let path = "/usr/bin:/bin:/usr/sbin:/sbin";
     command.env("PATH", path);
 
-    // Log command and environment for debugging
-    log_info(&format!("Running command: '{} {}'", cmd, args.join(" ")));
+    if let Some(dir) = chdir {
+        command.current_dir(dir);
+    }
 
     // Execute the command
     command.status()
 }
+
+nix::ioctl_read_bad!(ioctl_get_winsize, libc::TIOCGWINSZ, nix::pty::Winsize);
+nix::ioctl_write_ptr_bad!(ioctl_set_winsize, libc::TIOCSWINSZ, nix::pty::Winsize);
+
+fn get_window_size(fd: i32) -> Option<nix::pty::Winsize> {
+    let mut ws: nix::pty::Winsize = unsafe { std::mem::zeroed() };
+    unsafe { ioctl_get_winsize(fd, &mut ws) }.ok()?;
+    Some(ws)
+}
+
+fn set_window_size(fd: i32, ws: &nix::pty::Winsize) {
+    if let Err(e) = unsafe { ioctl_set_winsize(fd, ws) } {
+        log_warn(&format!("Failed to set PTY window size: {}", e));
+    }
+}
+
+static WINCH_RECEIVED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    WINCH_RECEIVED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Runs `cmd`/`args` as `target_user` inside a freshly allocated pseudo-terminal.
+fn run_command_pty(target_user: &str, cmd: &str, args: &[&str], chdir: Option<&Path>) -> Result<ExitStatus, std::io::Error> {
+    use nix::sys::termios::tcgetattr;
+
+    let stdin_fd = std::io::stdin().as_raw_fd();
+    let caller_termios = tcgetattr(stdin_fd).ok();
+    let caller_winsize = get_window_size(stdin_fd);
+
+    let pty = nix::pty::openpty(caller_winsize.as_ref(), caller_termios.as_ref()).map_err(|e| {
+        log_error(&format!("openpty failed: {}", e));
+        std::io::Error::new(std::io::ErrorKind::Other, format!("openpty failed: {}", e))
+    })?;
+
+    match unsafe { nix::unistd::fork() } {
+        Ok(nix::unistd::ForkResult::Child) => {
+            drop(pty.master);
+            let slave_fd = pty.slave.as_raw_fd();
+
+            let _ = nix::unistd::setsid();
+            unsafe {
+                if libc::ioctl(slave_fd, libc::TIOCSCTTY as _, 0) != 0 {
+                    log_error("Failed to set PTY slave as controlling terminal");
+                }
+            }
+
+            let _ = nix::unistd::dup2(slave_fd, 0);
+            let _ = nix::unistd::dup2(slave_fd, 1);
+            let _ = nix::unistd::dup2(slave_fd, 2);
+            drop(pty.slave);
+
+            if let Err(e) = switch_user(target_user) {
+                log_error(&format!("Failed to switch to user '{}': {}", target_user, e));
+                std::process::exit(1);
+            }
+
+            if let Some(dir) = chdir {
+                if let Err(e) = validate_chdir(dir) {
+                    log_error(&e);
+                    std::process::exit(1);
+                }
+            }
+
+            let mut child_cmd = Command::new(cmd);
+            child_cmd.args(args).env("PATH", "/usr/bin:/bin:/usr/sbin:/sbin");
+            if let Some(dir) = chdir {
+                child_cmd.current_dir(dir);
+            }
+            let err = child_cmd.exec();
+            log_error(&format!("Failed to exec '{}' in PTY: {}", cmd, err));
+            std::process::exit(127);
+        }
+        Ok(nix::unistd::ForkResult::Parent { child }) => {
+            drop(pty.slave);
+            let result = relay_pty(pty.master.as_raw_fd(), stdin_fd, child);
+            drop(pty.master);
+            result
+        }
+        Err(e) => {
+            log_error(&format!("fork failed: {}", e));
+            Err(std::io::Error::new(std::io::ErrorKind::Other, format!("fork failed: {}", e)))
+        }
+    }
+}
+
+/// Relays bytes between the PTY master and stdin/stdout until the child exits.
+fn relay_pty(master_fd: i32, stdin_fd: i32, child: nix::unistd::Pid) -> Result<ExitStatus, std::io::Error> {
+    use nix::errno::Errno;
+    use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::sys::signal::{signal, SigHandler, Signal};
+    use nix::unistd::{read, write as nix_write};
+    use std::os::fd::BorrowedFd;
+
+    unsafe {
+        let _ = signal(Signal::SIGWINCH, SigHandler::Handler(on_sigwinch));
+    }
+
+    let stdout_fd = std::io::stdout().as_raw_fd();
+    let mut buf = [0u8; 4096];
+
+    loop {
+        if WINCH_RECEIVED.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            if let Some(size) = get_window_size(stdin_fd) {
+                set_window_size(master_fd, &size);
+            }
+        }
+
+        let master_borrowed = unsafe { BorrowedFd::borrow_raw(master_fd) };
+        let stdin_borrowed = unsafe { BorrowedFd::borrow_raw(stdin_fd) };
+        let mut fds = [
+            PollFd::new(master_borrowed, PollFlags::POLLIN),
+            PollFd::new(stdin_borrowed, PollFlags::POLLIN),
+        ];
+
+        match poll(&mut fds, PollTimeout::from(100u16)) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(e) => {
+                log_error(&format!("poll failed on PTY relay: {}", e));
+                break;
+            }
+        }
+
+        if fds[0].revents().unwrap_or(PollFlags::empty()).contains(PollFlags::POLLIN) {
+            match read(master_fd, &mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let _ = nix_write(unsafe { BorrowedFd::borrow_raw(stdout_fd) }, &buf[..n]);
+                }
+                Err(Errno::EIO) => break, // slave closed
+                Err(Errno::EINTR) => {}
+                Err(e) => {
+                    log_error(&format!("Error reading from PTY master: {}", e));
+                    break;
+                }
+            }
+        }
+
+        if fds[1].revents().unwrap_or(PollFlags::empty()).contains(PollFlags::POLLIN) {
+            match read(stdin_fd, &mut buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    let _ = nix_write(unsafe { BorrowedFd::borrow_raw(master_fd) }, &buf[..n]);
+                }
+                Err(Errno::EINTR) => {}
+                Err(e) => log_error(&format!("Error reading from stdin: {}", e)),
+            }
+        }
+    }
+
+    match waitpid(child, None) {
+        Ok(WaitStatus::Exited(_, code)) => Ok(ExitStatus::from_raw(code << 8)),
+        Ok(WaitStatus::Signaled(_, sig, _)) => Ok(ExitStatus::from_raw(sig as i32)),
+        Ok(_) => Ok(ExitStatus::from_raw(0)),
+        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, format!("waitpid failed: {}", e))),
+    }
+}
@@ -1,9 +1,109 @@
 use log::{LevelFilter, Log, Metadata, Record, info, warn, debug, error};
-use syslog::{BasicLogger, Facility, Formatter3164};
+use syslog::{BasicLogger, Facility, Formatter3164, Logger, LoggerBackend};
 use std::sync::{Mutex, Once};
 
 static INIT: Once = Once::new();
 
+/// Syslog message bodies longer than this are split into parts by `log_audit`.
+const MAX_AUDIT_LINE: usize = 900;
+
+static AUDIT_LOGGER: Mutex<Option<Logger<LoggerBackend, Formatter3164>>> = Mutex::new(None);
+
+/// Maps a config-file facility name to its `syslog::Facility`, falling back to `LOG_AUTHPRIV`.
+fn facility_from_str(name: &str) -> Facility {
+    match name {
+        "auth" => Facility::LOG_AUTH,
+        "authpriv" => Facility::LOG_AUTHPRIV,
+        "daemon" => Facility::LOG_DAEMON,
+        "local0" => Facility::LOG_LOCAL0,
+        "local1" => Facility::LOG_LOCAL1,
+        "local2" => Facility::LOG_LOCAL2,
+        "local3" => Facility::LOG_LOCAL3,
+        "local4" => Facility::LOG_LOCAL4,
+        "local5" => Facility::LOG_LOCAL5,
+        "local6" => Facility::LOG_LOCAL6,
+        "local7" => Facility::LOG_LOCAL7,
+        other => {
+            warn!("Unrecognized syslog_facility '{}', defaulting to authpriv", other);
+            Facility::LOG_AUTHPRIV
+        }
+    }
+}
+
+/// Opens the dedicated audit sink used by `log_audit`, separate from `init_logger`.
+pub fn init_audit_logger(facility: &str, tag: &str) {
+    let formatter = Formatter3164 {
+        facility: facility_from_str(facility),
+        hostname: None,
+        process: tag.to_string(),
+        pid: std::process::id(),
+    };
+    match syslog::unix(formatter) {
+        Ok(logger) => *AUDIT_LOGGER.lock().unwrap() = Some(logger),
+        Err(e) => eprintln!("Failed to connect audit logger to syslog: {}", e),
+    }
+}
+
+/// One authorization decision, as recorded by `log_audit`.
+pub struct AuditRecord<'a> {
+    pub invoking_user: &'a str,
+    pub target_user: &'a str,
+    pub command: &'a str,
+    pub rule_priority: Option<u8>,
+    pub allowed: bool,
+}
+
+/// Records one authorization decision to the audit syslog sink as a `key=value` line.
+pub fn log_audit(record: &AuditRecord) {
+    let outcome = if record.allowed { "ALLOW" } else { "DENY" };
+    let priority = record
+        .rule_priority
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let message = format!(
+        "user={} target={} priority={} outcome={} command={}",
+        record.invoking_user, record.target_user, priority, outcome, record.command
+    );
+
+    let mut guard = AUDIT_LOGGER.lock().unwrap();
+    let Some(logger) = guard.as_mut() else {
+        warn!("Audit logger not initialized; dropping audit record: {}", message);
+        return;
+    };
+
+    let chunks = chunk_message(&message, MAX_AUDIT_LINE);
+    let total = chunks.len();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let line = if total > 1 {
+            format!("part={}/{} {}", i + 1, total, chunk)
+        } else {
+            chunk
+        };
+        if let Err(e) = logger.info(line) {
+            eprintln!("Failed to write audit record to syslog: {}", e);
+        }
+    }
+}
+
+/// Splits `message` into pieces of at most `max_len` bytes on UTF-8 boundaries.
+fn chunk_message(message: &str, max_len: usize) -> Vec<String> {
+    if message.len() <= max_len {
+        return vec![message.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < message.len() {
+        let mut end = (start + max_len).min(message.len());
+        while end < message.len() && !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(message[start..end].to_string());
+        start = end;
+    }
+    chunks
+}
+
 /// Initialize a global logger that writes to both syslog (if available)
 /// and the console. If `verbose` is true, debug-level logs are enabled.
 pub fn init_logger(verbose: bool) {
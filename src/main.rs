@@ -8,15 +8,16 @@ mod logs;
 
 use clap::{Arg, Command};
 use config::Config;
-use std::os::unix::process::CommandExt;
-use std::process::{exit, Command as ProcessCommand};
-use std::path::PathBuf;
-use util::get_user_groups;
-use auth::{verify_password, AuthState};
-use logs::{init_logger, log_info, log_warn, log_error};
-use nix::unistd::{getuid, geteuid, User};
+use std::process::exit;
+use util::resolve_user_groups;
+use auth::{verify_password, provision_local_credential, AuthState};
+use logs::{init_logger, init_audit_logger, log_audit, AuditRecord, log_info, log_warn, log_error};
+use nix::unistd::{getuid, geteuid, Gid, User};
 use nix::libc;
 use std::ffi::CStr;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use rpassword;
 
 /// Retrieve the real (invoking) user's username via their real UID.
 fn real_username() -> String {
@@ -66,26 +67,55 @@ fn main() {
             Arg::new("login")
                 .short('i')
                 .long("login")
-                .help("Run as login shell; skips command requirement")
-                .action(clap::ArgAction::SetTrue),
+                .help("Run as login shell")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("command"),
         )
         .arg(
             Arg::new("command")
-                .required_unless_present("login")
+                .required(false)
                 .num_args(1..)
                 .allow_hyphen_values(true)
                 .trailing_var_arg(true)
                 .value_name("COMMAND")
-                .help("Command to execute"),
+                .help("Command to execute; if omitted, launches the target user's login shell"),
         )
         .arg(
             Arg::new("clear-timestamp")
                 .short('K')
+                .short_alias('k')
                 .long("clear-timestamp")
                 .help("Clear authentication timestamp, forcing re-prompt on next use")
-                .action(clap::ArgAction::SetTrue))
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("no-persist")
+                .short('d')
+                .long("no-persist")
+                .help("Authenticate for this invocation only; don't cache the timestamp")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("pty")
+                .long("pty")
+                .help("Run the command inside an allocated pseudo-terminal")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("no-pty"),
+        )
+        .arg(
+            Arg::new("no-pty")
+                .long("no-pty")
+                .help("Never run the command inside a pseudo-terminal, overriding config")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("chdir")
+                .short('D')
+                .long("chdir")
+                .help("Working directory for the command (login mode defaults to the target's home instead)")
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(String)),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -93,6 +123,16 @@ fn main() {
                 .help("Enable verbose logging")
                 .action(clap::ArgAction::SetTrue),
         )
+        .subcommand(
+            Command::new("provision-credential")
+                .about("Hash a password and write/update its entry in the local credential file")
+                .arg(
+                    Arg::new("username")
+                        .required(true)
+                        .value_name("USER")
+                        .help("User to provision a local credential for"),
+                ),
+        )
         .get_matches();
 
     // Initialize logging
@@ -101,77 +141,179 @@ fn main() {
 
     // Who invoked elev
     let current_user = real_username();
-    let groups = get_user_groups(&current_user);
+    let primary_gid = User::from_name(&current_user)
+        .ok()
+        .flatten()
+        .map(|u| u.gid)
+        .unwrap_or_else(|| Gid::from_raw(0));
+    let groups = resolve_user_groups(&current_user, primary_gid);
 
-    // Handle clear-timestamp (-K)
-    if matches.get_flag("clear-timestamp") {
-        let stamp_path = PathBuf::from(format!("/run/elev/auth-{}.ts", current_user));
-        if std::fs::remove_file(&stamp_path).is_ok() {
-            println!("Authentication timestamp cleared.");
+    let config = Config::load("/etc/elev.conf").unwrap_or_else(|e| {
+        log_error(&format!("Failed to load config: {}", e));
+        exit(1);
+    });
+
+    init_audit_logger(&config.syslog_facility, &config.syslog_tag);
+
+    // Provision (or update) a local credential entry; runs as root since
+    // elev is setuid-root, so it can write the shadow-style file directly.
+    if let Some(sub) = matches.subcommand_matches("provision-credential") {
+        let username = sub.get_one::<String>("username").map(String::as_str).unwrap_or_default();
+
+        let mut prov_auth_state = AuthState::new(config.timeout, current_user.clone(), groups.clone(), &config);
+
+        // Provisioning your own credential just needs the usual auth gate
+        // below; provisioning someone else's (e.g. root's) additionally
+        // needs a rule explicitly granting it, same as running a command
+        // `as` that user would.
+        if username != current_user {
+            let decision = config.is_permitted(&current_user, &groups, username, "provision-credential", &prov_auth_state.roles);
+            if !decision.allowed {
+                log_error(&format!(
+                    "Permission denied for '{}' to provision a credential for '{}'", current_user, username
+                ));
+                eprintln!("elev: permission denied: provision-credential for '{}'", username);
+                exit(1);
+            }
+        }
+
+        if !prov_auth_state.check_timeout() && !verify_password(&current_user, &mut prov_auth_state, &config) {
+            log_error(&format!(
+                "Authentication failed; refusing to provision a credential for '{}'", username
+            ));
+            eprintln!("elev: authentication failed");
+            exit(1);
+        }
+
+        print!("New password for '{}': ", username);
+        io::stdout().flush().ok();
+        let password = rpassword::read_password().unwrap_or_default();
+        print!("Confirm password for '{}': ", username);
+        io::stdout().flush().ok();
+        let confirm = rpassword::read_password().unwrap_or_default();
+
+        if password != confirm {
+            eprintln!("elev: passwords do not match");
+            exit(1);
         }
+
+        let path = PathBuf::from(&config.auth_file_path);
+        match provision_local_credential(&path, username, &password) {
+            Ok(()) => {
+                println!("Provisioned local credential for '{}' in '{}'", username, path.display());
+                exit(0);
+            }
+            Err(e) => {
+                log_error(&format!("Failed to provision credential for '{}': {}", username, e));
+                exit(1);
+            }
+        }
+    }
+
+    // Handle clear-timestamp (-K/-k)
+    if matches.get_flag("clear-timestamp") {
+        let mut auth_state = AuthState::new(config.timeout, current_user.clone(), groups.clone(), &config);
+        auth_state.invalidate();
+        println!("Authentication timestamp cleared.");
         exit(0);
     }
 
+    let no_persist = matches.get_flag("no-persist");
+
     // Target user
     let target_user = matches.get_one::<String>("user").map(String::as_str).unwrap_or("root");
 
-    // Login shell mode (-i)
-    if matches.get_flag("login") {
-        // Switch user
-        if let Err(e) = exec::switch_user(target_user) {
-            log_error(&format!("Failed to switch to user '{}': {}", target_user, e));
-            exit(1);
-        }
-        // Lookup shell & home for target_user
-        let user_entry = match User::from_name(target_user) {
-            Ok(Some(u)) => u,
-            Ok(None) => { log_error(&format!("User '{}' not found", target_user)); exit(1); }
-            Err(e) => { log_error(&format!("Lookup failed for user '{}': {}", target_user, e)); exit(1); }
-        };
-        let home_dir = user_entry.dir;
-        let shell_path = user_entry.shell;
-
-        // Exec login shell
-        let mut shell = ProcessCommand::new(&shell_path);
-        shell.arg("-l");
-        shell.env("HOME", &home_dir);
-        shell.env("USER", target_user);
-        shell.env("LOGNAME", target_user);
-        shell.env("SHELL", &shell_path);
-        shell.env("PS1", r"\u@\h: \w\$ ");
-        shell.current_dir(&home_dir);
-        let err = shell.exec();
-        log_error(&format!("Failed to exec login shell: {}", err));
+    let chdir = matches.get_one::<String>("chdir").map(PathBuf::from);
+    if chdir.is_some() && !config.allow_chdir {
+        eprintln!("elev: --chdir is disabled by policy");
+        log_error("Rejected --chdir: disabled by config");
         exit(1);
     }
 
     // Collect command and args
     let parts = matches
         .get_many::<String>("command")
-        .expect("Command is required when not using -i")
-        .collect::<Vec<_>>();
-    let command = parts[0].as_str();
-    let args: Vec<&str> = parts[1..].iter().map(|s| s.as_str()).collect();
+        .map(|v| v.collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // Login shell mode: explicit -i, or no command given at all (elev as su).
+    // This still goes through the same permission/authentication gate below
+    // as any other command -- it's just policy-checked against the target's
+    // shell path instead of an argv[0].
+    let is_login = matches.get_flag("login") || parts.is_empty();
+
+    let login_shell: PathBuf;
+    let command = if is_login {
+        login_shell = exec::login_shell_path(target_user).unwrap_or_else(|e| {
+            log_error(&format!("Failed to resolve login shell for '{}': {}", target_user, e));
+            exit(1);
+        });
+        login_shell.to_string_lossy().into_owned()
+    } else {
+        parts[0].to_string()
+    };
+    let command = command.as_str();
+    let args: Vec<&str> = if is_login {
+        Vec::new()
+    } else {
+        parts[1..].iter().map(|s| s.as_str()).collect()
+    };
 
     log_info(&format!("elev invoked by '{}' to run '{}' as '{}'", current_user, command, target_user));
 
-    let config = Config::load("/etc/elev.conf").unwrap_or_else(|e| {
-        log_error(&format!("Failed to load config: {}", e));
-        exit(1);
+    let mut auth_state = AuthState::with_persist(config.timeout, current_user.clone(), groups.clone(), !no_persist, &config);
+
+    let decision = config.is_permitted(&current_user, &groups, target_user, command, &auth_state.roles);
+    log_audit(&AuditRecord {
+        invoking_user: &current_user,
+        target_user,
+        command,
+        rule_priority: decision.priority,
+        allowed: decision.allowed,
     });
-    let mut auth_state = AuthState::new(config.timeout, current_user.clone(), groups.clone());
+    if !decision.allowed {
+        eprintln!("elev: permission denied: '{}'", command);
+        log_error(&format!("Permission denied for '{}' to run '{}' as '{}'", current_user, command, target_user));
+        exit(1);
+    }
+
+    // A matching rule's `persist` keyword caches the auth timestamp for its
+    // commands even if this invocation passed --no-persist.
+    if decision.persist {
+        auth_state.persist = true;
+    }
 
-    // Enforce timeout & password
+    // Enforce timeout & password, unless the matching rule grants `nopass`.
     if !auth_state.check_timeout() {
-        log_warn("Authentication timeout expired, re-enter password.");
-        if !verify_password(&current_user, &mut auth_state, &config) {
-            log_error("Authentication failed");
-            exit(1);
+        if decision.nopass {
+            log_info(&format!("Matching rule grants nopass for '{}'; skipping password prompt", command));
+            // In-memory only, so run_command's timeout check passes; nothing is cached.
+            auth_state.last_authenticated = Some(std::time::Instant::now());
+        } else {
+            log_warn("Authentication timeout expired, re-enter password.");
+            if !verify_password(&current_user, &mut auth_state, &config) {
+                log_error("Authentication failed");
+                exit(1);
+            }
         }
     }
 
+    if is_login {
+        let err = exec::exec_login_shell(target_user, chdir.as_deref());
+        log_error(&format!("Failed to exec login shell: {}", err));
+        exit(1);
+    }
+
+    let use_pty = if matches.get_flag("no-pty") {
+        false
+    } else if matches.get_flag("pty") {
+        true
+    } else {
+        config.use_pty
+    };
+
     // Run the command
-    exec::run_command(&config, &mut auth_state, target_user, command, &args)
+    exec::run_command(&config, &mut auth_state, target_user, command, &args, use_pty, chdir.as_deref())
         .unwrap_or_else(|e| {
             use std::io::ErrorKind;
             match e.kind() {